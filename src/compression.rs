@@ -0,0 +1,117 @@
+//! Parsing of the `Accept-Encoding` header and selection of a content encoding.
+
+/// A content encoding that we know how to produce.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    /// The string used in the `Content-Encoding` header for this encoding.
+    pub fn header_value(&self) -> &'static str {
+        match *self {
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+/// Parses the value of an `Accept-Encoding` header and returns the encoding (amongst the ones
+/// we support) that the client prefers, if any.
+///
+/// Each entry of the header can carry a `q=` quality value between `0` and `1`; an encoding
+/// with a quality of `0` is explicitly refused by the client. When several supported encodings
+/// share the same quality, `gzip` is preferred over `deflate`.
+pub fn most_preferred_encoding(header: &str) -> Option<Encoding> {
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for entry in header.split(',') {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut parts = entry.split(';');
+        let name = parts.next().unwrap().trim();
+
+        let mut quality = 1.0f32;
+        for param in parts {
+            let param = param.trim();
+            if param.starts_with("q=") {
+                if let Ok(q) = param[2..].trim().parse() {
+                    quality = q;
+                }
+                break;
+            }
+        }
+
+        if quality <= 0.0 {
+            continue;
+        }
+
+        let encoding = match name {
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            _ => continue,
+        };
+
+        let better = match best {
+            None => true,
+            Some((_, best_quality)) if quality > best_quality => true,
+            Some((Encoding::Deflate, best_quality)) if quality == best_quality && encoding == Encoding::Gzip => true,
+            _ => false,
+        };
+
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, most_preferred_encoding};
+
+    #[test]
+    fn no_header_means_no_encoding() {
+        assert_eq!(most_preferred_encoding(""), None);
+    }
+
+    #[test]
+    fn picks_the_only_supported_encoding() {
+        assert_eq!(most_preferred_encoding("deflate"), Some(Encoding::Deflate));
+    }
+
+    #[test]
+    fn unsupported_encodings_are_ignored() {
+        assert_eq!(most_preferred_encoding("br, identity"), None);
+    }
+
+    #[test]
+    fn gzip_wins_ties() {
+        assert_eq!(most_preferred_encoding("deflate;q=0.8, gzip;q=0.8"), Some(Encoding::Gzip));
+    }
+
+    #[test]
+    fn higher_quality_wins_even_if_listed_first() {
+        assert_eq!(most_preferred_encoding("gzip;q=0.5, deflate;q=0.8"), Some(Encoding::Deflate));
+    }
+
+    #[test]
+    fn zero_quality_refuses_the_encoding() {
+        assert_eq!(most_preferred_encoding("gzip;q=0, deflate"), Some(Encoding::Deflate));
+    }
+
+    #[test]
+    fn all_refused_means_no_encoding() {
+        assert_eq!(most_preferred_encoding("gzip;q=0, deflate;q=0"), None);
+    }
+
+    #[test]
+    fn x_gzip_is_treated_as_gzip() {
+        assert_eq!(most_preferred_encoding("x-gzip"), Some(Encoding::Gzip));
+    }
+}
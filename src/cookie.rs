@@ -0,0 +1,181 @@
+//! Parsing of the `Cookie` request header and building of `Set-Cookie` response headers.
+
+/// The `SameSite` attribute of a cookie, as understood by modern browsers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    fn header_value(&self) -> &'static str {
+        match *self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Builds the value of a `Set-Cookie` header.
+///
+/// # Example
+///
+/// ```ignore
+/// let cookie = CookieBuilder::new("id", "abcdef").with_path("/").http_only().secure();
+/// let response = Response::text("hello").with_cookie(cookie);
+/// ```
+#[derive(Clone, Debug)]
+pub struct CookieBuilder {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<u32>,
+    expires: Option<String>,
+    http_only: bool,
+    secure: bool,
+    same_site: Option<SameSite>,
+}
+
+impl CookieBuilder {
+    /// Starts building a cookie with the given name and value.
+    #[inline]
+    pub fn new<N, V>(name: N, value: V) -> CookieBuilder where N: Into<String>, V: Into<String> {
+        CookieBuilder {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            http_only: false,
+            secure: false,
+            same_site: None,
+        }
+    }
+
+    /// Sets the `Path` attribute of the cookie.
+    #[inline]
+    pub fn with_path<P>(mut self, path: P) -> CookieBuilder where P: Into<String> {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// Sets the `Domain` attribute of the cookie.
+    #[inline]
+    pub fn with_domain<D>(mut self, domain: D) -> CookieBuilder where D: Into<String> {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Sets the `Max-Age` attribute of the cookie, in seconds.
+    #[inline]
+    pub fn with_max_age(mut self, max_age: u32) -> CookieBuilder {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Sets the `Expires` attribute of the cookie, already formatted as an HTTP date.
+    #[inline]
+    pub fn with_expires<E>(mut self, expires: E) -> CookieBuilder where E: Into<String> {
+        self.expires = Some(expires.into());
+        self
+    }
+
+    /// Marks the cookie as `HttpOnly`, preventing scripts from reading it.
+    #[inline]
+    pub fn http_only(mut self) -> CookieBuilder {
+        self.http_only = true;
+        self
+    }
+
+    /// Marks the cookie as `Secure`, so that browsers only send it over HTTPS.
+    #[inline]
+    pub fn secure(mut self) -> CookieBuilder {
+        self.secure = true;
+        self
+    }
+
+    /// Sets the `SameSite` attribute of the cookie.
+    #[inline]
+    pub fn same_site(mut self, same_site: SameSite) -> CookieBuilder {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Builds the value to put in a `Set-Cookie` header.
+    ///
+    /// Every attribute is sanitized first (see `sanitize_attribute`) so that a `;`, a `,`, or a
+    /// CR/LF smuggled in through `name`, `value`, or one of the other `Into<String>` attributes
+    /// can't break out of its own field and inject extra `Set-Cookie` attributes.
+    pub fn to_header_value(&self) -> String {
+        let mut out = format!("{}={}", sanitize_attribute(&self.name), sanitize_attribute(&self.value));
+
+        if let Some(ref path) = self.path {
+            out.push_str(&format!("; Path={}", sanitize_attribute(path)));
+        }
+        if let Some(ref domain) = self.domain {
+            out.push_str(&format!("; Domain={}", sanitize_attribute(domain)));
+        }
+        if let Some(max_age) = self.max_age {
+            out.push_str(&format!("; Max-Age={}", max_age));
+        }
+        if let Some(ref expires) = self.expires {
+            out.push_str(&format!("; Expires={}", sanitize_attribute(expires)));
+        }
+        if self.http_only {
+            out.push_str("; HttpOnly");
+        }
+        if self.secure {
+            out.push_str("; Secure");
+        }
+        if let Some(same_site) = self.same_site {
+            out.push_str(&format!("; SameSite={}", same_site.header_value()));
+        }
+
+        out
+    }
+}
+
+/// Strips everything that could let an attribute value break out of its own `Set-Cookie`
+/// field: `;` and `,` (attribute/cookie separators) and any control character, including
+/// CR/LF (header injection).
+fn sanitize_attribute(value: &str) -> String {
+    value.chars().filter(|&c| c != ';' && c != ',' && !c.is_control()).collect()
+}
+
+/// Parses the value of a `Cookie` header into a list of `(name, value)` pairs.
+pub fn parse_header(header: &str) -> Vec<(String, String)> {
+    let mut cookies = Vec::new();
+
+    for part in header.split(';') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+
+        let mut kv = part.splitn(2, '=');
+        let name = match kv.next() {
+            Some(name) => name,
+            None => continue,
+        };
+        let value = kv.next().unwrap_or("");
+
+        cookies.push((name.to_owned(), value.to_owned()));
+    }
+
+    cookies
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CookieBuilder;
+
+    #[test]
+    fn to_header_value_strips_separators_and_control_characters_from_attributes() {
+        let cookie = CookieBuilder::new("id", "abc;def,ghi\r\njkl").with_path("/foo;bar");
+        assert_eq!(cookie.to_header_value(), "id=abcdefghijkl; Path=/foobar");
+    }
+}
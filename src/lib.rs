@@ -1,31 +1,53 @@
+extern crate flate2;
+extern crate hmac;
+extern crate num_cpus;
 extern crate rand;
 extern crate rustc_serialize;
+extern crate sha2;
 extern crate time;
 extern crate tiny_http;
 extern crate url;
 
 pub use assets::match_assets;
+pub use cookie::{CookieBuilder, SameSite};
 pub use log::LogEntry;
 pub use input::{SessionsManager, Session, generate_session_id};
 
+use rustc_serialize::base64::FromBase64;
+use std::ascii::AsciiExt;
 use std::io;
 use std::io::Cursor;
 use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
 use std::error;
 use std::fmt;
 use std::fs::File;
 use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
+use std::panic::{self, AssertUnwindSafe};
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
 
 pub mod input;
 
 mod assets;
+mod compression;
+mod cookie;
 mod find_route;
 mod log;
 mod router;
+mod signed_cookie;
+
+/// Name of the cookie used by `Request::session_cookie` and `Response::with_session_cookie`.
+const SESSION_COOKIE_NAME: &'static str = "rouille_session";
+
+use compression::Encoding;
+use flate2::Compression;
+use flate2::read::{DeflateEncoder, GzEncoder};
 
 /// An error that one of your routes can return.
 ///
@@ -120,48 +142,199 @@ impl fmt::Display for RouteError {
 ///
 /// If your request handler panicks, a 500 error will automatically be sent to the client.
 ///
+/// # Concurrency
+///
+/// Requests are dispatched to a pool of worker threads so that several clients can be served
+/// at the same time. The number of threads defaults to the number of CPUs as reported by
+/// `num_cpus`. Use `start_server_with_pool` if you want to control this number yourself.
 pub fn start_server<A, F>(addr: A, handler: F) -> !
                           where A: ToSocketAddrs,
                                 F: Send + Sync + 'static + Fn(&Request) -> Response
 {
-    let server = tiny_http::ServerBuilder::new().with_port(8000).build().unwrap();
-
-    for mut request in server.incoming_requests() {
-        // TODO: don't read the body in memory immediately
-        let mut data = Vec::with_capacity(request.body_length().unwrap_or(0));
-        request.as_reader().read_to_end(&mut data);     // TODO: handle error
-
-        // building the `Request` object
-        let rouille_request = Request {
-            url: request.url().to_owned(),
-            method: request.method().as_str().to_owned(),
-            headers: request.headers().iter().map(|h| (h.field.to_string(), h.value.clone().into())).collect(),
-            https: false,
-            data: data,
-            remote_addr: request.remote_addr().clone(),
-        };
+    start_server_with_pool(addr, num_cpus::get(), handler)
+}
+
+/// Same as `start_server`, but lets you configure the number of worker threads used to
+/// dispatch requests.
+///
+/// The handler is wrapped in an `Arc` and shared between the threads of the pool. The pool
+/// has a fixed size: no matter how many connections come in, at most `num_threads` of them
+/// are being handled at any given time, which bounds the number of threads the server can
+/// spawn.
+///
+/// # Panic
+///
+/// Panics if `num_threads` is 0.
+pub fn start_server_with_pool<A, F>(addr: A, num_threads: usize, handler: F) -> !
+                                    where A: ToSocketAddrs,
+                                          F: Send + Sync + 'static + Fn(&Request) -> Response
+{
+    assert!(num_threads >= 1, "num_threads must be at least 1");
+
+    let addr = addr.to_socket_addrs().unwrap().next().expect("No socket addresses to bind to");
+    let server = Arc::new(tiny_http::ServerBuilder::new().with_addr(&addr).build().unwrap());
 
-        // calling the handler ; this most likely takes a lot of time
-        let mut rouille_response = handler(&rouille_request);
+    run_pool(server, num_threads, handler, false)
+}
+
+/// Starts a server that terminates TLS itself, so that `Request::secure` returns `true` for
+/// requests it receives. See `start_server` for the rest of the behavior.
+///
+/// `cert_chain` and `private_key` must be the PEM-encoded certificate chain and private key
+/// to present to clients.
+pub fn start_server_tls<A, F>(addr: A, cert_chain: Vec<u8>, private_key: Vec<u8>, handler: F) -> !
+                              where A: ToSocketAddrs,
+                                    F: Send + Sync + 'static + Fn(&Request) -> Response
+{
+    start_server_tls_with_pool(addr, num_cpus::get(), cert_chain, private_key, handler)
+}
+
+/// Same as `start_server_tls`, but lets you configure the number of worker threads used to
+/// dispatch requests. See `start_server_with_pool`.
+pub fn start_server_tls_with_pool<A, F>(addr: A, num_threads: usize, cert_chain: Vec<u8>,
+                                         private_key: Vec<u8>, handler: F) -> !
+                                        where A: ToSocketAddrs,
+                                              F: Send + Sync + 'static + Fn(&Request) -> Response
+{
+    assert!(num_threads >= 1, "num_threads must be at least 1");
+
+    let addr = addr.to_socket_addrs().unwrap().next().expect("No socket addresses to bind to");
+    let ssl = tiny_http::SslConfig { certificate: cert_chain, private_key: private_key };
+    let server = Arc::new(tiny_http::ServerBuilder::new().with_addr(&addr).with_ssl(ssl)
+                                                          .build().unwrap());
+
+    run_pool(server, num_threads, handler, true)
+}
+
+/// Spawns `num_threads` worker threads pulling requests off `server`, and joins them.
+fn run_pool<F>(server: Arc<tiny_http::Server>, num_threads: usize, handler: F, https: bool) -> !
+              where F: Send + Sync + 'static + Fn(&Request) -> Response
+{
+    let handler = Arc::new(handler);
 
-        // writing the response
-        let mut response = tiny_http::Response::empty(rouille_response.status_code)
-                    .with_data(rouille_response.data.data, rouille_response.data.data_length);
+    let workers: Vec<_> = (0 .. num_threads).map(|_| {
+        let server = server.clone();
+        let handler = handler.clone();
 
-        for (key, value) in rouille_response.headers {
-            if let Ok(header) = tiny_http::Header::from_bytes(key, value) {
-                response.add_header(header);
-            } else {
-                // TODO: ?
+        thread::spawn(move || {
+            // Backs off with an increasing delay while `server.recv()` keeps failing (e.g. the
+            // process is out of file descriptors), instead of spinning the worker at 100% CPU;
+            // reset as soon as a request comes through again.
+            let mut consecutive_errors = 0u32;
+
+            loop {
+                let request = match server.recv() {
+                    Ok(request) => request,
+                    Err(err) => {
+                        consecutive_errors += 1;
+                        eprintln!("rouille: error accepting a request: {}", err);
+                        let backoff_ms = 10u64.saturating_mul(1 << consecutive_errors.min(10));
+                        thread::sleep(Duration::from_millis(backoff_ms));
+                        continue;
+                    },
+                };
+
+                consecutive_errors = 0;
+                handle_request(request, &*handler, https);
             }
-        }
+        })
+    }).collect();
 
-        request.respond(response);
+    for worker in workers {
+        let _ = worker.join();
     }
 
     unreachable!()
 }
 
+/// Reads the request coming from tiny-http, calls the handler, and sends back the response.
+///
+/// `https` indicates whether the request was received on a TLS listener, and is reflected by
+/// `Request::secure`.
+fn handle_request<F>(request: tiny_http::Request, handler: &F, https: bool)
+                     where F: Fn(&Request) -> Response
+{
+    // `tiny_http::Request` fuses the request reader and the response writer into a single
+    // object, and `request.respond()` below needs to consume `request` by value, so it can't be
+    // borrowed by the handler and then moved here. Sharing it through an `Arc<Mutex<..>>` instead
+    // of buffering the whole body up front lets `LiveBody` stream straight from the socket as the
+    // handler reads it, while keeping `request` itself around (inside the `Arc`) for us to
+    // reclaim and pass to `respond()` once the handler returns.
+    let shared_request = Arc::new(Mutex::new(Some(request)));
+
+    let (url, method, headers, remote_addr) = {
+        let mut guard = shared_request.lock().unwrap();
+        let request = guard.as_mut().unwrap();
+        (request.url().to_owned(),
+         request.method().as_str().to_owned(),
+         request.headers().iter().map(|h| (h.field.to_string(), h.value.clone().into())).collect(),
+         request.remote_addr().clone())
+    };
+
+    let body: Box<Read + Send> = Box::new(LiveBody(shared_request.clone()));
+
+    // building the `Request` object
+    let rouille_request = Request {
+        url: url,
+        method: method,
+        headers: headers,
+        https: https,
+        data: Mutex::new(Some(body)),
+        remote_addr: remote_addr,
+    };
+
+    // calling the handler ; this most likely takes a lot of time
+    //
+    // a panicking handler (a bad `.unwrap()`, an out-of-range index, ...) must not take down
+    // the worker thread running it: workers never respawn, so a pool that keeps losing workers
+    // to panics would eventually have none left to call `server.recv()` and hang the whole
+    // server. Catch the panic and turn it into a 500 instead.
+    let mut rouille_response = match panic::catch_unwind(AssertUnwindSafe(|| handler(&rouille_request))) {
+        Ok(response) => response,
+        Err(_) => Response { status_code: 500, headers: vec![], data: ResponseBody::empty() },
+    };
+
+    // writing the response
+    let mut response = tiny_http::Response::empty(rouille_response.status_code)
+                .with_data(rouille_response.data.data, rouille_response.data.data_length);
+
+    for (key, value) in rouille_response.headers {
+        if let Ok(header) = tiny_http::Header::from_bytes(key, value) {
+            response.add_header(header);
+        } else {
+            // TODO: ?
+        }
+    }
+
+    // `LiveBody` only ever borrows `shared_request` to read from it, so by now the only other
+    // things that could still be holding a clone of the `Arc` are `rouille_request` (if the
+    // handler never took its body) and whatever the handler returned; `take()` reclaims the `tiny_http::Request` so we can respond with it. If the handler
+    // stashed the still-unconsumed `RequestBody` inside the response it returned instead of
+    // reading it to completion itself, `LiveBody::read` will see `None` here and report EOF for
+    // whatever was left unread, rather than dangling or blocking.
+    let request = shared_request.lock().unwrap().take();
+    if let Some(request) = request {
+        let _ = request.respond(response);
+    }
+}
+
+/// Reads the body of a live (non-fake) request straight from the connection, without buffering
+/// it into memory up front.
+///
+/// The underlying `tiny_http::Request` is shared with `handle_request`, which reclaims it once
+/// the handler returns in order to send the response back; reads after that point see an
+/// exhausted (empty) body instead of whatever was left unread.
+struct LiveBody(Arc<Mutex<Option<tiny_http::Request>>>);
+
+impl Read for LiveBody {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self.0.lock().unwrap() {
+            Some(ref mut request) => request.as_reader().read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
 /// Represents a request that your handler must answer to.
 ///
 /// This can be either a real request (received by the HTTP server) or a mock object created with
@@ -171,7 +344,7 @@ pub struct Request {
     url: String,
     headers: Vec<(String, String)>,
     https: bool,
-    data: Vec<u8>,
+    data: Mutex<Option<Box<Read + Send>>>,
     remote_addr: SocketAddr,
 }
 
@@ -187,7 +360,7 @@ impl Request {
             url: url.into(),
             method: method.into(),
             https: false,
-            data: data,
+            data: Mutex::new(Some(Box::new(Cursor::new(data)))),
             headers: headers,
             remote_addr: "127.0.0.1:12345".parse().unwrap(),
         }
@@ -202,7 +375,7 @@ impl Request {
             url: url.into(),
             method: method.into(),
             https: false,
-            data: data,
+            data: Mutex::new(Some(Box::new(Cursor::new(data)))),
             headers: headers,
             remote_addr: from,
         }
@@ -219,7 +392,7 @@ impl Request {
             url: url.into(),
             method: method.into(),
             https: true,
-            data: data,
+            data: Mutex::new(Some(Box::new(Cursor::new(data)))),
             headers: headers,
             remote_addr: "127.0.0.1:12345".parse().unwrap(),
         }
@@ -234,7 +407,7 @@ impl Request {
             url: url.into(),
             method: method.into(),
             https: true,
-            data: data,
+            data: Mutex::new(Some(Box::new(Cursor::new(data)))),
             headers: headers,
             remote_addr: from,
         }
@@ -271,17 +444,28 @@ impl Request {
 
     /// Returns the value of a header of the request.
     ///
+    /// The header name is matched case-insensitively, per RFC 7230 section 3.2.
+    ///
     /// Returns `None` if no such header could be found.
     #[inline]
     pub fn header(&self, key: &str) -> Option<String> {
-        self.headers.iter().find(|&&(ref k, _)| k == key).map(|&(_, ref v)| v.clone())
+        self.headers.iter().find(|&&(ref k, _)| k.eq_ignore_ascii_case(key)).map(|&(_, ref v)| v.clone())
     }
 
-    /// UNSTABLE. Returns the body of the request.
+    /// Returns the body of the request.
+    ///
+    /// The body is only accessible once: the first call to `data()` takes it out of the
+    /// `Request`, and any further call returns `None`. For a real request the returned object
+    /// streams straight from the underlying socket (nothing is buffered into memory up front, so
+    /// a large upload doesn't have to be read in its entirety before the handler sees any of it);
+    /// for a `fake_*` request it just reads out of the `Vec` that was passed in.
     ///
-    /// Will eventually return an object that implements `Read` instead of a `Vec<u8>`.
-    pub fn data(&self) -> Vec<u8> {
-        self.data.clone()
+    /// Reads must happen while the handler is still running: once it returns, the request is
+    /// handed off to send the response back, and the remaining body (if any wasn't read) is
+    /// treated as exhausted.
+    pub fn data(&self) -> Option<RequestBody> {
+        let mut data = self.data.lock().unwrap();
+        data.take().map(|data| RequestBody { data: data })
     }
 
     /// Returns the address of the client that made this request.
@@ -289,6 +473,136 @@ impl Request {
     pub fn remote_addr(&self) -> &SocketAddr {
         &self.remote_addr
     }
+
+    /// Parses the `Cookie` header of the request into a list of `(name, value)` pairs.
+    ///
+    /// Returns an empty `Vec` if the request has no `Cookie` header.
+    pub fn cookies(&self) -> Vec<(String, String)> {
+        match self.header("Cookie") {
+            Some(header) => cookie::parse_header(&header),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the value of the cookie named `name`, if present.
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().into_iter().find(|&(ref n, _)| n == name).map(|(_, v)| v)
+    }
+
+    /// Looks for a session cookie set by `Response::with_session_cookie`, checks its HMAC tag
+    /// against `secret` using a constant-time comparison, and returns the session id it
+    /// contains.
+    ///
+    /// Returns `None` if the cookie is missing, its signature doesn't match (for example
+    /// because it was signed with a different secret, or tampered with), or it has expired.
+    pub fn session_cookie(&self, secret: &[u8]) -> Option<String> {
+        let raw_value = match self.cookie(SESSION_COOKIE_NAME) {
+            Some(value) => value,
+            None => return None,
+        };
+
+        let payload = match signed_cookie::verify(&raw_value, secret) {
+            Some(payload) => payload,
+            None => return None,
+        };
+
+        let payload = match String::from_utf8(payload) {
+            Ok(payload) => payload,
+            Err(_) => return None,
+        };
+
+        let mut halves = payload.splitn(2, ':');
+        let id_len = match halves.next().and_then(|len| len.parse::<usize>().ok()) {
+            Some(id_len) => id_len,
+            None => return None,
+        };
+        let rest = match halves.next() {
+            Some(rest) => rest,
+            None => return None,
+        };
+        if rest.len() < id_len || !rest.is_char_boundary(id_len) {
+            return None;
+        }
+        let (id, expires_at) = rest.split_at(id_len);
+        let expires_at = match expires_at.parse::<i64>().ok() {
+            Some(expires_at) => expires_at,
+            None => return None,
+        };
+
+        if expires_at < time::get_time().sec {
+            return None;
+        }
+
+        Some(id.to_owned())
+    }
+
+    /// Parses the `Authorization: Basic <base64>` header and returns the decoded
+    /// `(username, password)` pair.
+    ///
+    /// Returns `None` if the header is absent, isn't `Basic`, or is malformed.
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let header = match self.header("Authorization") {
+            Some(header) => header,
+            None => return None,
+        };
+
+        // The `auth-scheme` token (`Basic`) is case-insensitive per RFC 7235, so `basic ...` and
+        // `BASIC ...` must be accepted just as well as `Basic ...`. Matching on bytes rather
+        // than slicing the `str` first avoids panicking if a non-matching header happens to
+        // have a multi-byte character straddling the byte-6 boundary.
+        if !header.as_bytes().get(..6).map_or(false, |prefix| prefix.eq_ignore_ascii_case(b"Basic ")) {
+            return None;
+        }
+
+        let decoded = match header[6..].from_base64() {
+            Ok(decoded) => decoded,
+            Err(_) => return None,
+        };
+
+        let decoded = match String::from_utf8(decoded) {
+            Ok(decoded) => decoded,
+            Err(_) => return None,
+        };
+
+        let mut parts = decoded.splitn(2, ':');
+        let username = match parts.next() {
+            Some(username) => username,
+            None => return None,
+        };
+        let password = parts.next().unwrap_or("");
+
+        Some((username.to_owned(), password.to_owned()))
+    }
+
+    /// Parses the `Authorization: Bearer <token>` header and returns the token.
+    ///
+    /// Returns `None` if the header is absent or isn't `Bearer`.
+    pub fn bearer_token(&self) -> Option<String> {
+        let header = match self.header("Authorization") {
+            Some(header) => header,
+            None => return None,
+        };
+
+        // Same as in `basic_auth`: the `auth-scheme` token is case-insensitive, and matched on
+        // bytes first so a non-matching header can't panic on a byte-7 char boundary.
+        if !header.as_bytes().get(..7).map_or(false, |prefix| prefix.eq_ignore_ascii_case(b"Bearer ")) {
+            return None;
+        }
+
+        Some(header[7..].to_owned())
+    }
+}
+
+/// Streams the body of a `Request`. See `Request::data`.
+pub struct RequestBody {
+    data: Box<Read + Send>,
+}
+
+impl Read for RequestBody {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
+    }
 }
 
 /// Contains a prototype of a response.
@@ -396,6 +710,307 @@ impl Response {
         self.status_code = code;
         self
     }
+
+    /// Compresses the body of the response according to the `Accept-Encoding` header of the
+    /// given request.
+    ///
+    /// If the client advertises support for `gzip` or `deflate` (via the `Accept-Encoding`
+    /// header, including `q=` quality values), the body is wrapped in a streaming encoder, the
+    /// appropriate `Content-Encoding` header is set, and any stale `Content-Length` header is
+    /// removed (the compressed length isn't known in advance, so the response will be sent
+    /// using chunked transfer encoding).
+    ///
+    /// If the client doesn't support any encoding we know how to produce, the response is
+    /// returned unmodified.
+    pub fn with_compression(self, request: &Request) -> Response {
+        let encoding = request.header("Accept-Encoding")
+                               .and_then(|header| compression::most_preferred_encoding(&header));
+
+        match encoding {
+            Some(Encoding::Gzip) => self.with_encoding(Encoding::Gzip.header_value(), |data| {
+                ResponseBody::from_reader(GzEncoder::new(data, Compression::Default))
+            }),
+            Some(Encoding::Deflate) => self.with_encoding(Encoding::Deflate.header_value(), |data| {
+                ResponseBody::from_reader(DeflateEncoder::new(data, Compression::Default))
+            }),
+            None => self,
+        }
+    }
+
+    /// Builds a `Response` that serves `file`, honoring `Range`, `If-Range`,
+    /// `If-Modified-Since` and `ETag` so that large files can be resumed and re-validated by the
+    /// client instead of always being sent in full with a `200`.
+    ///
+    /// This is a standalone primitive: it's not wired into `match_assets`, so serving a
+    /// directory of static assets with range/conditional support means opening the matched
+    /// file yourself and calling this function instead of `match_assets`.
+    ///
+    /// The `ETag` is computed from the file's size and modification time, and is weak (it
+    /// doesn't guarantee byte-for-byte equality, only that the file probably hasn't changed) ;
+    /// it's tagged `W/` accordingly. Per RFC 7233 section 3.2, `If-Range` may only be honored against
+    /// a strong validator, so a weak `ETag` can never satisfy it: a range request carrying
+    /// `If-Range` always falls back to a full `200` response instead of resuming.
+    pub fn from_file_ranged(request: &Request, file: File) -> Response {
+        let metadata = match file.metadata() {
+            Ok(metadata) => metadata,
+            Err(_) => return response_from_file(file),
+        };
+
+        let total_len = metadata.len();
+        let etag = format!("W/\"{:x}-{:x}\"", total_len, file_mtime_secs(&metadata));
+
+        if let Some(if_none_match) = request.header("If-None-Match") {
+            if if_none_match == etag {
+                return Response { status_code: 304, headers: vec![], data: ResponseBody::empty() };
+            }
+        } else if let Some(if_modified_since) = request.header("If-Modified-Since") {
+            let not_modified = time::strptime(&if_modified_since, "%a, %d %b %Y %H:%M:%S GMT")
+                                    .map(|since| file_mtime_secs(&metadata) as i64 <= since.to_timespec().sec)
+                                    .unwrap_or(false);
+            if not_modified {
+                return Response { status_code: 304, headers: vec![], data: ResponseBody::empty() };
+            }
+        }
+
+        let mut range = request.header("Range")
+                                .map(|header| parse_byte_range(&header, total_len))
+                                .unwrap_or(ByteRange::NotRequested);
+
+        // `If-Range` must be evaluated with a strong comparison (RFC 7233 section 3.2), and our
+        // `ETag` is always weak, so it can never satisfy `If-Range`: fall back to sending the
+        // whole file rather than risk resuming a range against a file that changed without its
+        // size or modification time changing within the same second.
+        if request.header("If-Range").is_some() {
+            range = ByteRange::NotRequested;
+        }
+
+        match range {
+            ByteRange::Satisfiable(start, end) => {
+                let mut file = file;
+                if file.seek(SeekFrom::Start(start)).is_err() {
+                    return response_from_file(file);
+                }
+
+                let len = end - start + 1;
+                Response {
+                    status_code: 206,
+                    headers: vec![
+                        ("Content-Range".to_owned(), format!("bytes {}-{}/{}", start, end, total_len)),
+                        ("Accept-Ranges".to_owned(), "bytes".to_owned()),
+                        ("ETag".to_owned(), etag),
+                    ],
+                    data: ResponseBody {
+                        data: Box::new(file.take(len)),
+                        data_length: Some(len as usize),
+                    },
+                }
+            },
+            ByteRange::Unsatisfiable => Response {
+                status_code: 416,
+                headers: vec![("Content-Range".to_owned(), format!("bytes */{}", total_len))],
+                data: ResponseBody::empty(),
+            },
+            ByteRange::NotRequested => {
+                let mut response = response_from_file(file);
+                response.headers.push(("Accept-Ranges".to_owned(), "bytes".to_owned()));
+                response.headers.push(("ETag".to_owned(), etag));
+                response
+            },
+        }
+    }
+
+    /// Sets a cookie, built with a `CookieBuilder`, on the response.
+    #[inline]
+    pub fn with_cookie(mut self, cookie: CookieBuilder) -> Response {
+        self.headers.push(("Set-Cookie".to_owned(), cookie.to_header_value()));
+        self
+    }
+
+    /// Sets a signed session cookie carrying `id`, valid for `max_age` seconds.
+    ///
+    /// The cookie's value is `base64url(payload) + "." + base64url(HMAC-SHA256(secret, payload))`
+    /// where `payload` embeds `id` and its expiry time, so the server doesn't need to keep
+    /// track of outstanding cookies to validate them later (see `Request::session_cookie`).
+    /// `payload` is `"{id.len()}:{id}{expires_at}"`, a length-prefixed encoding, so `id` may
+    /// contain any bytes (including `:`) without corrupting the expiry that follows it.
+    /// The cookie is set with `HttpOnly` and `SameSite=Lax`, and with `Secure` if `request` was
+    /// received over HTTPS.
+    pub fn with_session_cookie(self, request: &Request, id: &str, secret: &[u8], max_age: u32)
+                               -> Response
+    {
+        let expires_at = time::get_time().sec + max_age as i64;
+        let payload = format!("{}:{}{}", id.len(), id, expires_at);
+        let signed = signed_cookie::sign(payload.as_bytes(), secret);
+
+        let mut cookie = CookieBuilder::new(SESSION_COOKIE_NAME, signed)
+                                        .with_path("/")
+                                        .with_max_age(max_age)
+                                        .http_only()
+                                        .same_site(SameSite::Lax);
+        if request.secure() {
+            cookie = cookie.secure();
+        }
+
+        self.with_cookie(cookie)
+    }
+
+    /// Wraps the response's body using `wrap`, sets `Content-Encoding: encoding` and `Vary:
+    /// Accept-Encoding`, and removes any `Content-Length` header the caller may have set
+    /// manually.
+    ///
+    /// `Vary: Accept-Encoding` tells caches that the response depends on the request's
+    /// `Accept-Encoding` header, so a cache won't serve this encoded response to a later client
+    /// that never claimed to support it.
+    fn with_encoding<F>(mut self, encoding: &str, wrap: F) -> Response
+                        where F: FnOnce(Box<Read + Send>) -> ResponseBody
+    {
+        self.headers.retain(|&(ref key, _)| !key.eq_ignore_ascii_case("Content-Length"));
+        self.headers.push(("Content-Encoding".to_owned(), encoding.to_owned()));
+        self.headers.push(("Vary".to_owned(), "Accept-Encoding".to_owned()));
+        self.data = wrap(self.data.data);
+        self
+    }
+}
+
+/// Builds a plain, unconditional `Response` that serves the whole of `file` with a `200`.
+fn response_from_file(file: File) -> Response {
+    Response { status_code: 200, headers: vec![], data: ResponseBody::from_file(file) }
+}
+
+/// The result of parsing a `Range` header against the total length of the resource.
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// There was no `Range` header, or it couldn't be honored (e.g. a multi-range request),
+    /// in which case the whole resource should be sent.
+    NotRequested,
+    /// A single, satisfiable byte range, inclusive on both ends.
+    Satisfiable(u64, u64),
+    /// The range couldn't be satisfied given the resource's length.
+    Unsatisfiable,
+}
+
+/// Parses a single `bytes=start-end` range out of the value of a `Range` header.
+fn parse_byte_range(header: &str, total_len: u64) -> ByteRange {
+    if !header.starts_with("bytes=") || header[6..].contains(',') {
+        // multiple ranges aren't supported ; send the whole file instead
+        return ByteRange::NotRequested;
+    }
+
+    let spec = &header[6..];
+    let mut parts = spec.splitn(2, '-');
+    let start = parts.next().unwrap_or("");
+    let end = parts.next().unwrap_or("");
+
+    let (start, end) = if start.is_empty() {
+        // a suffix range such as "-500" means the last 500 bytes of the resource
+        let suffix_len = match end.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => return ByteRange::NotRequested,
+        };
+
+        if suffix_len == 0 || total_len == 0 {
+            return ByteRange::Unsatisfiable;
+        }
+
+        (total_len.saturating_sub(suffix_len), total_len - 1)
+    } else {
+        let start = match start.parse::<u64>() {
+            Ok(n) => n,
+            Err(_) => return ByteRange::NotRequested,
+        };
+
+        let end = if end.is_empty() {
+            total_len.saturating_sub(1)
+        } else {
+            match end.parse::<u64>() {
+                Ok(n) => n,
+                Err(_) => return ByteRange::NotRequested,
+            }
+        };
+
+        (start, end)
+    };
+
+    if total_len == 0 || start > end || start >= total_len {
+        return ByteRange::Unsatisfiable;
+    }
+
+    ByteRange::Satisfiable(start, if end >= total_len { total_len - 1 } else { end })
+}
+
+#[cfg(test)]
+mod byte_range_tests {
+    use super::{parse_byte_range, ByteRange};
+
+    #[test]
+    fn full_start_end_range() {
+        assert_eq!(parse_byte_range("bytes=0-499", 1000), ByteRange::Satisfiable(0, 499));
+    }
+
+    #[test]
+    fn open_ended_range() {
+        assert_eq!(parse_byte_range("bytes=500-", 1000), ByteRange::Satisfiable(500, 999));
+    }
+
+    #[test]
+    fn suffix_range() {
+        assert_eq!(parse_byte_range("bytes=-500", 1000), ByteRange::Satisfiable(500, 999));
+    }
+
+    #[test]
+    fn suffix_range_longer_than_the_resource_is_clamped() {
+        assert_eq!(parse_byte_range("bytes=-5000", 1000), ByteRange::Satisfiable(0, 999));
+    }
+
+    #[test]
+    fn end_past_total_len_is_clamped() {
+        assert_eq!(parse_byte_range("bytes=0-5000", 1000), ByteRange::Satisfiable(0, 999));
+    }
+
+    #[test]
+    fn start_past_total_len_is_unsatisfiable() {
+        assert_eq!(parse_byte_range("bytes=1000-1500", 1000), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn start_after_end_is_unsatisfiable() {
+        assert_eq!(parse_byte_range("bytes=500-100", 1000), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn zero_length_suffix_is_unsatisfiable() {
+        assert_eq!(parse_byte_range("bytes=-0", 1000), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn empty_resource_is_unsatisfiable() {
+        assert_eq!(parse_byte_range("bytes=0-0", 0), ByteRange::Unsatisfiable);
+    }
+
+    #[test]
+    fn multiple_ranges_are_not_supported() {
+        assert_eq!(parse_byte_range("bytes=0-10,20-30", 1000), ByteRange::NotRequested);
+    }
+
+    #[test]
+    fn malformed_unit_is_not_requested() {
+        assert_eq!(parse_byte_range("items=0-10", 1000), ByteRange::NotRequested);
+    }
+
+    #[test]
+    fn malformed_numbers_are_not_requested() {
+        assert_eq!(parse_byte_range("bytes=abc-10", 1000), ByteRange::NotRequested);
+    }
+}
+
+/// Returns a file's modification time as a number of seconds since the Unix epoch, or `0` if
+/// it can't be determined.
+fn file_mtime_secs(metadata: &::std::fs::Metadata) -> u64 {
+    metadata.modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
 }
 
 /// An opaque type that represents the body of a response.
@@ -416,8 +1031,8 @@ impl ResponseBody {
 
     /// Builds a new `ResponseBody` that will read the data from a `Read`.
     ///
-    /// Note that this is suboptimal compared to other constructors because the length
-    /// isn't known in advance.
+    /// Since the length of the data isn't known in advance, the response will be sent to
+    /// the client using HTTP chunked transfer encoding instead of a `Content-Length` header.
     #[inline]
     pub fn from_reader<R>(data: R) -> ResponseBody where R: Read + Send + 'static {
         ResponseBody {
@@ -0,0 +1,105 @@
+//! HMAC-signed payloads, used to authenticate the content of a cookie without needing any
+//! server-side storage.
+
+use hmac::{Hmac, Mac};
+use rustc_serialize::base64::{FromBase64, ToBase64, URL_SAFE};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs `payload` with `secret` and returns `base64url(payload) + "." + base64url(tag)`.
+pub fn sign(payload: &[u8], secret: &[u8]) -> String {
+    let tag = hmac_tag(payload, secret);
+    format!("{}.{}", payload.to_base64(URL_SAFE), tag.to_base64(URL_SAFE))
+}
+
+/// Verifies a value produced by `sign` and, if the signature matches, returns the decoded
+/// payload.
+///
+/// The signature is checked using a constant-time comparison so that an attacker trying to
+/// forge a cookie can't use the time taken by the comparison to guess the tag one byte at a
+/// time.
+pub fn verify(value: &str, secret: &[u8]) -> Option<Vec<u8>> {
+    let dot = match value.rfind('.') {
+        Some(pos) => pos,
+        None => return None,
+    };
+
+    let payload = match value[..dot].from_base64() {
+        Ok(payload) => payload,
+        Err(_) => return None,
+    };
+
+    let tag = match value[dot + 1..].from_base64() {
+        Ok(tag) => tag,
+        Err(_) => return None,
+    };
+
+    if constant_time_eq(&hmac_tag(&payload, secret), &tag) {
+        Some(payload)
+    } else {
+        None
+    }
+}
+
+fn hmac_tag(payload: &[u8], secret: &[u8]) -> Vec<u8> {
+    let mut hmac = HmacSha256::new_varkey(secret).expect("HMAC accepts a key of any length");
+    hmac.input(payload);
+    hmac.result().code().to_vec()
+}
+
+/// Compares two byte slices in constant time.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut diff = 0u8;
+    for (x, y) in a.iter().zip(b.iter()) {
+        diff |= x ^ y;
+    }
+
+    diff == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify};
+
+    #[test]
+    fn round_trips_through_sign_and_verify() {
+        let value = sign(b"session-id|1234", b"secret");
+        assert_eq!(verify(&value, b"secret"), Some(b"session-id|1234".to_vec()));
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let mut value = sign(b"session-id|1234", b"secret");
+        let dot = value.find('.').unwrap();
+        value.replace_range(..dot, "dGFtcGVyZWQ");
+        assert_eq!(verify(&value, b"secret"), None);
+    }
+
+    #[test]
+    fn rejects_a_tampered_tag() {
+        let mut value = sign(b"session-id|1234", b"secret");
+        value.push('x');
+        assert_eq!(verify(&value, b"secret"), None);
+    }
+
+    #[test]
+    fn rejects_the_wrong_secret() {
+        let value = sign(b"session-id|1234", b"secret");
+        assert_eq!(verify(&value, b"wrong secret"), None);
+    }
+
+    #[test]
+    fn rejects_a_value_with_no_dot() {
+        assert_eq!(verify("not-a-signed-value", b"secret"), None);
+    }
+
+    #[test]
+    fn rejects_malformed_base64() {
+        assert_eq!(verify("not base64!.also not base64!", b"secret"), None);
+    }
+}